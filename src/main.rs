@@ -1,13 +1,23 @@
 use anyhow::{anyhow, bail, Context, Result};
 use log::{error, info};
-use nix::{sys::signal, unistd::Pid};
-use std::process::ExitCode;
+use nix::{
+    sys::signal,
+    sys::signal::Signal,
+    sys::wait::{waitpid, WaitPidFlag, WaitStatus},
+    unistd::Pid,
+};
+use std::collections::HashMap;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{ExitCode, ExitStatus};
+use std::str::FromStr;
 use tokio::{
-    fs::{DirEntry, File},
-    io::{self, AsyncBufReadExt},
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
     process::Command,
     select,
-    time::{sleep, Duration},
+    signal::unix::{signal as unix_signal, SignalKind},
+    time::{sleep, Duration, Instant},
 };
 
 // Single threaded async Rust is used so we don't have to deal with Linux
@@ -42,105 +52,866 @@ async fn main() -> Result<ExitCode> {
         bail!("No command provided");
     }
 
+    // Set up a dedicated cgroup for race-free whole-tree teardown if asked
+    // to and the kernel supports it, falling back to the subreaper+scan
+    // strategy below otherwise.
+    let cgroup = Cgroup::new().await.context("setting up cgroup")?;
+
+    // Optionally capture the child's stdout/stderr and log it, in addition
+    // to passing it through untouched.
+    let capture = CaptureConfig::from_env();
+
+    // Built before we spawn anything, so a bad config (e.g. an invalid
+    // ANAKIN_KILL_SIGNAL/ANAKIN_KILL_TIMEOUT) is reported before the child is
+    // launched, rather than leaving it running unsupervised while `?` unwinds
+    // out of `main`.
+    let mut escalator = Escalator::new().context("configuring kill escalation")?;
+
     let mut command = Command::new(&args[0]);
     command.args(&args[1..]);
+    if capture.stdout {
+        command.stdout(std::process::Stdio::piped());
+    }
+    if capture.stderr {
+        command.stderr(std::process::Stdio::piped());
+    }
+
+    if let Some(cgroup) = &cgroup {
+        // SAFETY: see `join_on_exec`'s doc comment.
+        unsafe { cgroup.join_on_exec(&mut command) };
+    }
 
     let mut child = command.spawn().context("spawning subprocess")?;
 
     let child_id = child.id().ok_or(anyhow!("error getting child PID"))?;
 
-    let reaper = kill_children_forever(child_id);
+    let mut capture_tasks = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        capture_tasks.push(tokio::spawn(capture_stream(
+            stdout,
+            "stdout",
+            child_id,
+            tokio::io::stdout(),
+        )));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        capture_tasks.push(tokio::spawn(capture_stream(
+            stderr,
+            "stderr",
+            child_id,
+            tokio::io::stderr(),
+        )));
+    }
+
+    // We don't additionally wait on the child via its own pidfd: tokio's
+    // `Child::wait` already reaps it through an edge-triggered,
+    // SIGCHLD-driven future, so there's no listen-then-reap race here to
+    // close -- that race is specific to orphans we only learn about via
+    // periodic `/proc` enumeration, not our own direct child.
     let child_wait = child.wait();
 
-    let exit_code = select! {
-        () = reaper => { unreachable!() },
-        res = child_wait => { res?.code().unwrap_or(1) },
+    let exit_code = if cgroup.is_some() {
+        // No need to scan /proc and escalate signals against orphans
+        // ourselves: the cgroup already captures every descendant, however
+        // deep it forks or reparents, and cgroup.kill handles killing them
+        // all atomically on the way out. We still need to reap the ones
+        // that exit on their own in the meantime, or they'd sit around as
+        // zombies (we're the subreaper) for the rest of the supervised run.
+        let reaper = reap_children_forever(child_id);
+        let forwarder = forward_signals_forever(child_id);
+        select! {
+            res = reaper => { res?; unreachable!() },
+            sig = forwarder => {
+                exit_code(wait_or_force_kill(&mut child, child_id, sig?, escalator.timeout).await?)
+            },
+            res = child_wait => { exit_code(res?) },
+        }
+    } else {
+        let reaper = kill_children_forever(child_id, &mut escalator);
+        let forwarder = forward_signals_forever(child_id);
+        select! {
+            res = reaper => { res?; unreachable!() },
+            sig = forwarder => {
+                exit_code(wait_or_force_kill(&mut child, child_id, sig?, escalator.timeout).await?)
+            },
+            res = child_wait => { exit_code(res?) },
+        }
     };
 
-    // Final cleanup of orphans. Don't kill process 0 (which shouldn't exist).
-    kill_children(0).await?;
+    // Final cleanup of orphans, before draining the capture tasks below: a
+    // reparented descendant that inherited the child's piped stdout/stderr
+    // fd can keep that pipe open indefinitely, so the capture tasks can't
+    // see EOF on it until any such orphan is gone.
+    if let Some(cgroup) = &cgroup {
+        cgroup.kill_all().await?;
+        // `cgroup.kill` only signals; anything left still needs waitpid'ing
+        // by us (we're the subreaper), or it stays a cgroup member forever
+        // and `Cgroup::drop`'s rmdir retry loop never succeeds.
+        loop {
+            for pid in get_children(0).await? {
+                if let Err(e) = reap_if_exited(pid) {
+                    error!("{e}");
+                }
+            }
+            if get_children(0).await?.is_empty() {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+    } else {
+        // Don't kill process 0 (which shouldn't exist).
+        escalator.reap_until_gone(0).await?;
+    }
+
+    // Now that nothing can still be holding the pipes open, let the capture
+    // tasks drain whatever's left before we exit.
+    for task in capture_tasks {
+        if let Err(e) = task.await {
+            error!("capture task panicked: {e}");
+        }
+    }
 
     Ok(ExitCode::from(exit_code as u8))
 }
 
-/// Loop forever killing all direct children except the given process.
-async fn kill_children_forever(except: u32) {
+/// Which of the child's standard streams to capture, configured via
+/// `ANAKIN_CAPTURE` (a comma-separated list of `stdout`, `stderr`).
+#[derive(Debug, PartialEq, Eq)]
+struct CaptureConfig {
+    stdout: bool,
+    stderr: bool,
+}
+
+impl CaptureConfig {
+    fn from_env() -> CaptureConfig {
+        CaptureConfig::parse(&std::env::var("ANAKIN_CAPTURE").unwrap_or_default())
+    }
+
+    /// Parse the `ANAKIN_CAPTURE` value itself, split out from `from_env` so
+    /// the parsing logic can be tested without touching the environment.
+    fn parse(streams: &str) -> CaptureConfig {
+        let mut config = CaptureConfig {
+            stdout: false,
+            stderr: false,
+        };
+        for stream in streams.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match stream {
+                "stdout" => config.stdout = true,
+                "stderr" => config.stderr = true,
+                other => error!("ignoring unknown ANAKIN_CAPTURE stream '{other}'"),
+            }
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod capture_config_tests {
+    use super::CaptureConfig;
+
+    #[test]
+    fn empty_captures_nothing() {
+        assert_eq!(
+            CaptureConfig::parse(""),
+            CaptureConfig {
+                stdout: false,
+                stderr: false
+            }
+        );
+    }
+
+    #[test]
+    fn single_stream() {
+        assert_eq!(
+            CaptureConfig::parse("stdout"),
+            CaptureConfig {
+                stdout: true,
+                stderr: false
+            }
+        );
+    }
+
+    #[test]
+    fn both_streams_with_whitespace() {
+        assert_eq!(
+            CaptureConfig::parse(" stdout , stderr "),
+            CaptureConfig {
+                stdout: true,
+                stderr: true
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_stream_is_ignored_not_fatal() {
+        assert_eq!(
+            CaptureConfig::parse("stdout,bogus"),
+            CaptureConfig {
+                stdout: true,
+                stderr: false
+            }
+        );
+    }
+}
+
+/// Read `stream` line by line, logging each line tagged with the child's PID
+/// and which stream it came from, while also copying it back out to `tee`
+/// (normally anakin's own stdout/stderr).
+///
+/// Not a transparent passthrough: piping makes fd 1/2 a non-TTY from the
+/// child's point of view, so `isatty()`-gated colour output disables itself;
+/// and `tee` always gains a trailing `\n`, even if the child's last write
+/// didn't have one.
+async fn capture_stream<R, W>(stream: R, label: &'static str, pid: u32, mut tee: W)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    // Split on raw bytes rather than `lines()`: a child is free to write
+    // arbitrary, possibly non-UTF-8 bytes (binary output, a non-UTF-8
+    // locale, ...), and `lines()` hard-errors the moment it hits one,
+    // permanently killing passthrough along with logging for the rest of
+    // the child's life. `from_utf8_lossy` only affects what we log; `tee`
+    // always gets the original bytes back, unmodified.
+    let mut chunks = BufReader::new(stream).split(b'\n');
+    loop {
+        match chunks.next_segment().await {
+            Ok(Some(chunk)) => {
+                info!(
+                    target: "anakin::capture",
+                    "[{pid}:{label}] {}",
+                    String::from_utf8_lossy(&chunk)
+                );
+                let teed = async {
+                    tee.write_all(&chunk).await?;
+                    tee.write_all(b"\n").await?;
+                    tee.flush().await
+                };
+                if let Err(e) = teed.await {
+                    error!("writing {label} passthrough for child {pid}: {e}");
+                }
+            }
+            Ok(None) => return,
+            Err(e) => {
+                error!("reading {label} from child {pid}: {e}");
+                return;
+            }
+        }
+    }
+}
+
+/// Convert a child's exit status into a process exit code, following the
+/// conventional shell encoding when terminated by a signal (e.g. 137 for
+/// `SIGKILL`, 143 for `SIGTERM`) rather than flattening that case to 1.
+fn exit_code(status: ExitStatus) -> i32 {
+    match status.code() {
+        Some(code) => code,
+        None => 128 + status.signal().unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod exit_code_tests {
+    use super::exit_code;
+    use nix::sys::signal::Signal;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    #[test]
+    fn normal_exit_passes_code_through() {
+        assert_eq!(exit_code(ExitStatus::from_raw(42 << 8)), 42);
+    }
+
+    #[test]
+    fn sigterm_maps_to_shell_convention() {
+        assert_eq!(exit_code(ExitStatus::from_raw(Signal::SIGTERM as i32)), 143);
+    }
+
+    #[test]
+    fn sigkill_maps_to_shell_convention() {
+        assert_eq!(exit_code(ExitStatus::from_raw(Signal::SIGKILL as i32)), 137);
+    }
+}
+
+/// A dedicated cgroup v2 used for race-free teardown of the whole descendant
+/// tree, instead of scanning `/proc` and `SIGKILL`ing PIDs one by one.
+/// Enabled by setting `ANAKIN_CGROUP=1`.
+///
+/// Writing `1` to the cgroup's `cgroup.kill` file asks the kernel to kill
+/// every process in the cgroup atomically, closing the PID-reuse race that
+/// scan-then-kill has.
+struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Create a fresh cgroup under our own delegated cgroup v2 hierarchy, if
+    /// `ANAKIN_CGROUP=1` is set and the kernel supports it. Returns `None`
+    /// (and logs why) if cgroup v2 isn't mounted, the controller isn't
+    /// delegated to us, or `cgroup.kill` doesn't exist (kernel < 5.14) -- the
+    /// caller should fall back to the subreaper+scan strategy in that case.
+    async fn new() -> Result<Option<Cgroup>> {
+        if std::env::var("ANAKIN_CGROUP").as_deref() != Ok("1") {
+            return Ok(None);
+        }
+
+        let own_cgroup = tokio::fs::read_to_string("/proc/self/cgroup")
+            .await
+            .context("reading /proc/self/cgroup")?;
+
+        // A cgroup v2 entry looks like "0::/some/path".
+        let Some(own_path) = own_cgroup.lines().find_map(|line| line.strip_prefix("0::")) else {
+            info!("cgroup v2 not available, falling back to /proc scanning");
+            return Ok(None);
+        };
+
+        let parent = PathBuf::from("/sys/fs/cgroup").join(own_path.trim_start_matches('/'));
+
+        if !parent.join("cgroup.kill").exists() {
+            info!(
+                "{} has no cgroup.kill (kernel < 5.14 or controller not delegated), \
+                 falling back to /proc scanning",
+                parent.display()
+            );
+            return Ok(None);
+        }
+
+        let path = parent.join(format!("anakin-{}", std::process::id()));
+        match tokio::fs::create_dir(&path).await {
+            Ok(()) => Ok(Some(Cgroup { path })),
+            // `cgroup.kill` existing only tells us the kernel is new enough,
+            // not that this subtree is actually delegated to us -- a
+            // non-delegated cgroup is read-only to us, so creating our own
+            // cgroup underneath it fails with EACCES/EPERM.
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                info!(
+                    "{} is not delegated to us ({e}), falling back to /proc scanning",
+                    parent.display()
+                );
+                Ok(None)
+            }
+            Err(e) => Err(e).with_context(|| anyhow!("creating cgroup {}", path.display())),
+        }
+    }
+
+    /// Install a `pre_exec` hook on `command` that joins this cgroup from
+    /// inside the child, between `fork` and `exec`, instead of joining it
+    /// from out here after `spawn()` returns. By the time `spawn()` returns
+    /// the child may already have exec'd and forked a grandchild of its own,
+    /// which would then be left in our ambient cgroup rather than this one;
+    /// joining before `exec` closes that window entirely.
+    ///
+    /// # Safety
+    /// The closure runs in the forked child, where only async-signal-safe
+    /// operations are sound in general. The plain `std::fs::write` below is
+    /// safe only because every `tokio::fs` call is awaited to completion
+    /// before `spawn()` is reached, so no tokio blocking-pool thread can be
+    /// outstanding at fork time -- not because `current_thread` guarantees
+    /// single-threadedness. A future `tokio::fs` call added near `spawn()`
+    /// without being awaited first could silently reintroduce the deadlock.
+    unsafe fn join_on_exec(&self, command: &mut Command) {
+        let cgroup_procs = self.path.join("cgroup.procs");
+        // SAFETY: see this function's doc comment.
+        unsafe {
+            command.pre_exec(move || std::fs::write(&cgroup_procs, std::process::id().to_string()));
+        }
+    }
+
+    /// Atomically kill every process in the cgroup.
+    async fn kill_all(&self) -> Result<()> {
+        tokio::fs::write(self.path.join("cgroup.kill"), "1")
+            .await
+            .with_context(|| anyhow!("killing cgroup {}", self.path.display()))
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // The directory can only be removed once `cgroup.procs` is empty,
+        // which may take an instant after `cgroup.kill`, so retry briefly.
+        for attempt in 0..50 {
+            match std::fs::remove_dir(&self.path) {
+                Ok(()) => return,
+                Err(_) if attempt < 49 => std::thread::sleep(Duration::from_millis(20)),
+                Err(e) => error!("removing cgroup {}: {e}", self.path.display()),
+            }
+        }
+    }
+}
+
+/// Relay signals received by anakin to the main child, so a signal sent to
+/// anakin (e.g. by an init system, or Ctrl-C) reaches the real workload
+/// instead of leaving the child to be abruptly reparented.
+///
+/// `SIGUSR1`/`SIGUSR2` are forwarded forever. The rest are conventionally
+/// requests to terminate, so after forwarding one of those this returns it
+/// to the caller, which enforces a grace period rather than waiting on the
+/// child forever.
+async fn forward_signals_forever(child_pid: u32) -> Result<Signal> {
+    let mut term = unix_signal(SignalKind::terminate()).context("registering SIGTERM handler")?;
+    let mut int = unix_signal(SignalKind::interrupt()).context("registering SIGINT handler")?;
+    let mut hup = unix_signal(SignalKind::hangup()).context("registering SIGHUP handler")?;
+    let mut quit = unix_signal(SignalKind::quit()).context("registering SIGQUIT handler")?;
+    let mut usr1 =
+        unix_signal(SignalKind::user_defined1()).context("registering SIGUSR1 handler")?;
+    let mut usr2 =
+        unix_signal(SignalKind::user_defined2()).context("registering SIGUSR2 handler")?;
+
     loop {
-        if let Err(e) = kill_children(except).await {
+        let sig = select! {
+            _ = term.recv() => Signal::SIGTERM,
+            _ = int.recv() => Signal::SIGINT,
+            _ = hup.recv() => Signal::SIGHUP,
+            _ = quit.recv() => Signal::SIGQUIT,
+            _ = usr1.recv() => Signal::SIGUSR1,
+            _ = usr2.recv() => Signal::SIGUSR2,
+        };
+
+        info!("forwarding {sig} to child {child_pid}");
+        send_signal(child_pid, sig).context("forwarding signal to child")?;
+
+        if matches!(
+            sig,
+            Signal::SIGTERM | Signal::SIGINT | Signal::SIGHUP | Signal::SIGQUIT
+        ) {
+            return Ok(sig);
+        }
+    }
+}
+
+/// After forwarding a would-be-terminating `sig` to `child`, give it the
+/// same grace period as orphan escalation to exit on its own; if it hasn't
+/// by then, force it with `SIGKILL` rather than waiting indefinitely, so
+/// anakin can't outlive a signal it claims to honour just because the
+/// workload ignored it.
+async fn wait_or_force_kill(
+    child: &mut tokio::process::Child,
+    child_id: u32,
+    sig: Signal,
+    timeout: Duration,
+) -> Result<ExitStatus> {
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => Ok(status?),
+        Err(_) => {
+            info!("child {child_id} didn't exit within the grace period after {sig}, killing it");
+            send_signal(child_id, Signal::SIGKILL).context("force-killing child")?;
+            Ok(child.wait().await?)
+        }
+    }
+}
+
+/// Loop forever reaping all direct children except the given process.
+///
+/// Rather than polling on a fixed timer, this wakes up whenever we receive
+/// `SIGCHLD` (i.e. as soon as some child, orphaned or not, exits or is
+/// reparented to us), so orphans are reaped almost immediately. A longer
+/// timer is kept as a safety net in case a `SIGCHLD` is missed (signals can
+/// coalesce, so two children dying close together may only generate one).
+async fn kill_children_forever(except: u32, escalator: &mut Escalator) -> Result<()> {
+    let mut sigchld = unix_signal(SignalKind::child()).context("registering SIGCHLD handler")?;
+    loop {
+        if let Err(e) = escalator.reap(except).await {
             error!("{e}");
         }
-        sleep(Duration::from_millis(1000)).await;
+        select! {
+            _ = sigchld.recv() => {},
+            () = sleep(escalator.next_wake()) => {},
+        }
     }
 }
 
-/// Loop, killing all the children except the given process ID.
-async fn kill_children(except: u32) -> Result<()> {
-    /// Process a /proc/??? entry.
-    async fn process_entry(entry: DirEntry, my_pid: u32, except: u32) -> Result<()> {
-        // Check if the entry is a directory and represents a process ID
-        if !entry.file_type().await?.is_dir() {
-            return Ok(());
+/// Loop forever reaping all direct children except the given process,
+/// without signalling any of them.
+///
+/// Used alongside cgroup supervision, where `cgroup.kill` is what actually
+/// terminates the whole tree on the way out: this just keeps collecting
+/// descendants that exit on their own in the meantime, so they don't sit
+/// around as zombies (we're the subreaper) for the rest of the supervised
+/// run. As with `kill_children_forever`, `SIGCHLD` drives prompt reaping,
+/// with a timer as a safety net in case a `SIGCHLD` is missed.
+async fn reap_children_forever(except: u32) -> Result<()> {
+    let mut sigchld = unix_signal(SignalKind::child()).context("registering SIGCHLD handler")?;
+    const SAFETY_NET: Duration = Duration::from_secs(5);
+    loop {
+        for pid in get_children(except).await? {
+            if let Err(e) = reap_if_exited(pid) {
+                error!("{e}");
+            }
         }
+        select! {
+            _ = sigchld.recv() => {},
+            () = sleep(SAFETY_NET) => {},
+        }
+    }
+}
 
-        let file_name = entry.file_name();
-        let file_name = file_name.to_string_lossy();
+/// Escalates orphaned children from a configurable "please exit" signal to
+/// `SIGKILL` if they don't exit within a grace period, instead of signalling
+/// `SIGKILL` unconditionally.
+///
+/// The first signal defaults to `SIGTERM` (`ANAKIN_KILL_SIGNAL`); the grace
+/// period defaults to 5 seconds (`ANAKIN_KILL_TIMEOUT`).
+struct Escalator {
+    first_signal: Signal,
+    timeout: Duration,
+    /// When each currently-known orphan was first signalled, so escalation
+    /// survives across reaper iterations. Entries are dropped once the PID
+    /// is no longer among our children.
+    signaled_at: HashMap<u32, Instant>,
+}
 
-        if let Ok(pid) = file_name.parse::<u32>() {
-            if pid == except {
-                return Ok(());
+impl Escalator {
+    fn new() -> Result<Escalator> {
+        let first_signal = match std::env::var("ANAKIN_KILL_SIGNAL") {
+            Ok(s) => {
+                Signal::from_str(&s).with_context(|| anyhow!("invalid ANAKIN_KILL_SIGNAL '{s}'"))?
             }
-            // Read the stat file for the process
-            let stat_file = match File::open(format!("/proc/{file_name}/stat")).await {
-                // Ignore file not found errors which can occur due to races with processes exiting.
-                Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
-                other => other,
-            }
-            .with_context(|| anyhow!("opening /proc/{file_name}/stat"))?;
-            let mut reader = io::BufReader::new(stat_file);
-            let mut buf = String::new();
-            reader
-                .read_line(&mut buf)
-                .await
-                .with_context(|| anyhow!("reading /proc/{file_name}/stat"))?;
-
-            // Extract the parent process ID from the stat file
-            let parent_pid: Option<u32> =
-                buf.split_whitespace().nth(3).and_then(|s| s.parse().ok());
-
-            // Check if it's a child process and not the exception
-            if let Some(parent_pid) = parent_pid {
-                if parent_pid == my_pid {
-                    // Kill the child process
-                    info!(
-                        "killing orphan {pid}: {}",
-                        get_command_line(pid).unwrap_or("?".to_string())
+            Err(_) => Signal::SIGTERM,
+        };
+        let timeout = match std::env::var("ANAKIN_KILL_TIMEOUT") {
+            Ok(s) => {
+                let secs: f64 = s
+                    .parse()
+                    .with_context(|| anyhow!("invalid ANAKIN_KILL_TIMEOUT '{s}'"))?;
+                if !secs.is_finite() || secs < 0.0 {
+                    bail!(
+                        "invalid ANAKIN_KILL_TIMEOUT '{s}': must be a non-negative, finite \
+                         number of seconds"
                     );
-                    // Get its command line.
-                    signal::kill(Pid::from_raw(pid as i32), signal::SIGKILL)
-                        .context("sending kill signal to process")?;
                 }
+                Duration::from_secs_f64(secs)
             }
+            Err(_) => Duration::from_secs(5),
+        };
+        Ok(Escalator {
+            first_signal,
+            timeout,
+            signaled_at: HashMap::new(),
+        })
+    }
+
+    /// Signal all direct children of this process except `except`: the
+    /// configured first signal the first time we see a PID, escalating to
+    /// `SIGKILL` once it's survived the grace period.
+    async fn reap(&mut self, except: u32) -> Result<()> {
+        let children = get_children(except).await?;
+        self.signaled_at.retain(|pid, _| children.contains(pid));
+
+        for pid in children {
+            // Reap it first: a zombie keeps showing up in `get_children`
+            // until we do, and doesn't need further signalling.
+            if reap_if_exited(pid)? {
+                self.signaled_at.remove(&pid);
+                continue;
+            }
+
+            let Some((sig, verb)) =
+                Self::escalation(self.first_signal, self.timeout, self.signaled_at.get(&pid))
+            else {
+                continue;
+            };
+
+            info!(
+                "{verb} orphan {pid} with {sig}: {}",
+                get_command_line(pid).unwrap_or("?".to_string())
+            );
+            self.signaled_at.entry(pid).or_insert_with(Instant::now);
+
+            send_signal(pid, sig).context("sending signal to process")?;
         }
         Ok(())
     }
 
-    let my_pid = std::process::id();
+    /// Keep reaping `except`'s children, escalating as usual, until none are
+    /// left. Used for the final cleanup pass where there's no longer a
+    /// reaper loop running in the background to do it.
+    async fn reap_until_gone(&mut self, except: u32) -> Result<()> {
+        loop {
+            self.reap(except).await?;
+            if get_children(except).await?.is_empty() {
+                return Ok(());
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
 
-    // Open the directory containing process information
-    let mut entries = tokio::fs::read_dir("/proc")
+    /// How long the reaper loop can sleep before it must wake up on its own
+    /// to escalate a still-living child, rather than waiting on `SIGCHLD`.
+    ///
+    /// Floored at `MIN_WAKE` so an orphan that's survived its grace period
+    /// but hasn't died yet (e.g. stuck in uninterruptible sleep) doesn't spin
+    /// the reaper loop re-signalling it on every tick.
+    fn next_wake(&self) -> Duration {
+        const SAFETY_NET: Duration = Duration::from_secs(5);
+        const MIN_WAKE: Duration = Duration::from_millis(200);
+        self.signaled_at
+            .values()
+            .map(|signaled_at| self.timeout.saturating_sub(signaled_at.elapsed()))
+            .min()
+            .map_or(SAFETY_NET, |remaining| {
+                remaining.clamp(MIN_WAKE, SAFETY_NET)
+            })
+    }
+
+    /// Decide what, if anything, to do about a still-alive orphan. Split out
+    /// of `reap` as a pure function so it's testable without a real child.
+    fn escalation(
+        first_signal: Signal,
+        timeout: Duration,
+        signaled_at: Option<&Instant>,
+    ) -> Option<(Signal, &'static str)> {
+        match signaled_at {
+            None => Some((first_signal, "signalling")),
+            Some(signaled_at) if signaled_at.elapsed() >= timeout => {
+                Some((Signal::SIGKILL, "killing"))
+            }
+            Some(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod escalator_tests {
+    use super::Escalator;
+    use nix::sys::signal::Signal;
+    use tokio::time::{Duration, Instant};
+
+    #[test]
+    fn first_sighting_sends_first_signal() {
+        assert_eq!(
+            Escalator::escalation(Signal::SIGTERM, Duration::from_secs(5), None),
+            Some((Signal::SIGTERM, "signalling"))
+        );
+    }
+
+    #[test]
+    fn within_grace_period_does_nothing() {
+        let signaled_at = Instant::now();
+        assert_eq!(
+            Escalator::escalation(Signal::SIGTERM, Duration::from_secs(5), Some(&signaled_at)),
+            None
+        );
+    }
+
+    #[test]
+    fn past_grace_period_escalates_to_sigkill() {
+        let signaled_at = Instant::now() - Duration::from_secs(10);
+        assert_eq!(
+            Escalator::escalation(Signal::SIGTERM, Duration::from_secs(5), Some(&signaled_at)),
+            Some((Signal::SIGKILL, "killing"))
+        );
+    }
+
+    #[test]
+    fn next_wake_defaults_to_safety_net_with_no_orphans() {
+        let escalator = Escalator {
+            first_signal: Signal::SIGTERM,
+            timeout: Duration::from_secs(5),
+            signaled_at: Default::default(),
+        };
+        assert_eq!(escalator.next_wake(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn next_wake_is_bounded_by_the_soonest_escalation() {
+        let mut escalator = Escalator {
+            first_signal: Signal::SIGTERM,
+            timeout: Duration::from_secs(5),
+            signaled_at: Default::default(),
+        };
+        escalator
+            .signaled_at
+            .insert(123, Instant::now() - Duration::from_secs(4));
+        assert!(escalator.next_wake() <= Duration::from_secs(1));
+    }
+}
+
+/// Reap `pid` with a non-blocking `waitpid` if it has already exited.
+///
+/// Nothing else ever waits on orphans, so a killed one would otherwise sit
+/// around as a zombie forever. Returns whether `pid` was reaped.
+fn reap_if_exited(pid: u32) -> Result<bool> {
+    match waitpid(Pid::from_raw(pid as i32), Some(WaitPidFlag::WNOHANG)) {
+        Ok(WaitStatus::StillAlive) => Ok(false),
+        Ok(_) => Ok(true),
+        // Already reaped by someone else (shouldn't happen, but harmless).
+        Err(nix::errno::Errno::ECHILD) => Ok(true),
+        Err(e) => Err(e).with_context(|| anyhow!("waitpid({pid})")),
+    }
+}
+
+/// Return the PIDs of all direct children of this process except `except`.
+///
+/// Reads the kernel-maintained `children` file under each of our threads'
+/// `/proc/self/task/<tid>/` directories, rather than scanning all of `/proc`.
+/// Our own code runs on one thread, but `tokio::fs` (used here and elsewhere)
+/// runs on tokio's blocking-pool threads, so there can be several `tid`s and
+/// an orphan may be reparented to any of them; we iterate all of them.
+async fn get_children(except: u32) -> Result<Vec<u32>> {
+    let mut children = Vec::new();
+
+    let mut tasks = tokio::fs::read_dir("/proc/self/task")
         .await
-        .context("reading /proc")?;
+        .context("reading /proc/self/task")?;
 
-    // Iterate over each entry in the directory
-    while let Some(entry) = entries.next_entry().await.context("reading dir entry")? {
-        if let Err(e) = process_entry(entry, my_pid, except).await {
-            error!("{e}");
+    while let Some(task) = tasks.next_entry().await.context("reading task dir entry")? {
+        let tid = task.file_name();
+        let path = format!("/proc/self/task/{}/children", tid.to_string_lossy());
+
+        let contents = match tokio::fs::read_to_string(&path).await {
+            // The thread may have exited between listing it and reading its children.
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            other => other,
+        }
+        .with_context(|| anyhow!("reading {path}"))?;
+
+        for pid in contents.split_whitespace() {
+            if let Ok(pid) = pid.parse::<u32>() {
+                if pid != except {
+                    children.push(pid);
+                }
+            }
         }
     }
 
+    Ok(children)
+}
+
+/// Signal `pid`, preferring a pidfd over a raw PID where the kernel supports
+/// it, to avoid signalling a PID that's since been recycled.
+///
+/// `pidfd_open` itself still resolves whatever process currently owns `pid`,
+/// so it can already be the wrong one; re-check it's still one of our own
+/// children before trusting it.
+fn send_signal(pid: u32, sig: Signal) -> Result<()> {
+    let pidfd = match PidFd::open(pid) {
+        // `pid` is simply gone already: nothing left to signal.
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .and_then(std::io::Error::raw_os_error)
+                == Some(libc::ESRCH) =>
+        {
+            return Ok(());
+        }
+        other => other.context("opening pidfd")?,
+    };
+    match pidfd {
+        Some(pidfd) => {
+            if ppid(pid)? != Some(std::process::id()) {
+                // `pid` exited and was recycled into some other process
+                // before we got the pidfd open (or has since been reaped);
+                // either way it's not ours to signal.
+                return Ok(());
+            }
+            // A `false` return just means the process already exited, which
+            // is exactly what we want here: nothing left to signal.
+            pidfd.send_signal(sig).context("pidfd_send_signal")?;
+        }
+        None => {
+            // Kernel predates pidfd_open (< 5.3): fall back to the old,
+            // reuse-racy but universally supported PID-based signal.
+            if let Err(e) = signal::kill(Pid::from_raw(pid as i32), sig) {
+                // The process may have exited between listing and signalling it.
+                if e != nix::errno::Errno::ESRCH {
+                    return Err(e).context("sending signal to process");
+                }
+            }
+        }
+    }
     Ok(())
 }
 
+/// A `pidfd`: a file descriptor referring to one specific process instance,
+/// as opposed to a PID, which the kernel is free to recycle once the
+/// process has exited and been reaped.
+struct PidFd(OwnedFd);
+
+impl PidFd {
+    /// Open a pidfd for `pid`. Returns `Ok(None)` if the kernel doesn't
+    /// support `pidfd_open` (introduced in Linux 5.3), so the caller can
+    /// fall back to PID-based operations.
+    fn open(pid: u32) -> Result<Option<PidFd>> {
+        // SAFETY: pidfd_open(2) with flags 0 just takes a PID and returns a
+        // new, owned fd (or -1/errno on failure); no other preconditions.
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+        if fd < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::ENOSYS) => Ok(None),
+                _ => Err(err).with_context(|| anyhow!("pidfd_open({pid})")),
+            };
+        }
+        // SAFETY: pidfd_open just returned this as a new, owned fd.
+        Ok(Some(PidFd(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })))
+    }
+
+    /// Send `sig` to the process this pidfd refers to. Returns `Ok(false)`,
+    /// rather than an `ESRCH` error, if that process has already exited --
+    /// crucially, this can never happen because some *other, reused* PID
+    /// took its place, since the pidfd keeps referring to this instance.
+    fn send_signal(&self, sig: Signal) -> Result<bool> {
+        // pidfd_send_signal was added in Linux 5.1, i.e. before pidfd_open
+        // (5.3), so if we got this far the kernel is new enough to have it.
+        // libc doesn't expose its syscall number for glibc targets yet, but
+        // it has been stable at 424 since introduction on every architecture
+        // except the legacy ones with their own syscall tables.
+        #[cfg(any(target_arch = "mips", target_arch = "mips32r6"))]
+        const SYS_PIDFD_SEND_SIGNAL: libc::c_long = 4000 + 424;
+        #[cfg(any(target_arch = "mips64", target_arch = "mips64r6"))]
+        const SYS_PIDFD_SEND_SIGNAL: libc::c_long = 5000 + 424;
+        #[cfg(not(any(
+            target_arch = "mips",
+            target_arch = "mips32r6",
+            target_arch = "mips64",
+            target_arch = "mips64r6"
+        )))]
+        const SYS_PIDFD_SEND_SIGNAL: libc::c_long = 424;
+
+        // SAFETY: valid pidfd, no siginfo_t, no flags -- equivalent to kill().
+        let ret = unsafe {
+            libc::syscall(
+                SYS_PIDFD_SEND_SIGNAL,
+                self.0.as_raw_fd(),
+                sig as libc::c_int,
+                std::ptr::null::<libc::siginfo_t>(),
+                0,
+            )
+        };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::ESRCH) => Ok(false),
+                _ => Err(err).context("pidfd_send_signal"),
+            };
+        }
+        Ok(true)
+    }
+}
+
+/// Return `pid`'s parent PID, or `None` if `pid` no longer exists.
+fn ppid(pid: u32) -> Result<Option<u32>> {
+    let stat = match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        other => other,
+    }
+    .with_context(|| anyhow!("reading /proc/{pid}/stat"))?;
+
+    // The command name field is parenthesised and may itself contain spaces
+    // or parens, so skip past its closing paren rather than splitting
+    // naively; state is the first whitespace-separated field after that,
+    // ppid the second.
+    let after_comm = stat
+        .rfind(')')
+        .ok_or_else(|| anyhow!("malformed /proc/{pid}/stat: {stat:?}"))?;
+    let ppid = stat[after_comm + 1..]
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| anyhow!("malformed /proc/{pid}/stat: {stat:?}"))?;
+    Ok(Some(ppid.parse().with_context(|| {
+        anyhow!("parsing ppid from /proc/{pid}/stat")
+    })?))
+}
+
 fn get_command_line(pid: u32) -> Result<String> {
     let cmdline = std::fs::read_to_string(format!("/proc/{pid}/cmdline"))?;
     Ok(cmdline